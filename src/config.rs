@@ -0,0 +1,84 @@
+//! Layered configuration: defaults, overridden by `pfm.toml` (or
+//! `~/.config/pfm/config.toml`), overridden by environment variables.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{fs, path::PathBuf};
+
+const DEFAULT_API_URL: &str = "https://api.easyportfol.io";
+const DEFAULT_QUOTE_CURRENCY: &str = "USD";
+const DEFAULT_STATE_PATH: &str = "state.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+struct FileConfig {
+    api_url: Option<String>,
+    quote_currency: Option<String>,
+    state_path: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub api_url: String,
+    pub quote_currency: String,
+    pub state_path: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            api_url: DEFAULT_API_URL.to_string(),
+            quote_currency: DEFAULT_QUOTE_CURRENCY.to_string(),
+            state_path: DEFAULT_STATE_PATH.to_string(),
+        }
+    }
+}
+
+fn config_file_candidates() -> Vec<PathBuf> {
+    let mut candidates = vec![PathBuf::from("pfm.toml")];
+
+    if let Some(home) = dirs::home_dir() {
+        candidates.push(home.join(".config/pfm/config.toml"));
+    }
+
+    candidates
+}
+
+fn read_file_config() -> Result<FileConfig> {
+    for path in config_file_candidates() {
+        if path.exists() {
+            let contents = fs::read_to_string(&path)?;
+            return Ok(toml::from_str(&contents)?);
+        }
+    }
+
+    Ok(FileConfig::default())
+}
+
+/// Loads config by layering defaults, then a config file, then environment
+/// variables, in increasing order of precedence.
+pub fn load() -> Result<Config> {
+    let mut config = Config::default();
+    let file_config = read_file_config()?;
+
+    if let Some(api_url) = file_config.api_url {
+        config.api_url = api_url;
+    }
+    if let Some(quote_currency) = file_config.quote_currency {
+        config.quote_currency = quote_currency;
+    }
+    if let Some(state_path) = file_config.state_path {
+        config.state_path = state_path;
+    }
+
+    if let Ok(api_url) = std::env::var("PFM_API_URL") {
+        config.api_url = api_url;
+    }
+    if let Ok(quote_currency) = std::env::var("PFM_QUOTE_CURRENCY") {
+        config.quote_currency = quote_currency;
+    }
+    if let Ok(state_path) = std::env::var("PFM_STATE_PATH") {
+        config.state_path = state_path;
+    }
+
+    Ok(config)
+}
@@ -0,0 +1,46 @@
+//! Logging setup: stderr always, plus an optional timestamped file sink and,
+//! when built with `enable_syslog`, a syslog sink for cron-driven runs.
+
+use anyhow::Result;
+use log::LevelFilter;
+
+pub fn init(level: LevelFilter, log_file: Option<&str>) -> Result<()> {
+    let mut dispatch = fern::Dispatch::new()
+        .format(|out, message, record| {
+            out.finish(format_args!(
+                "[{} {} {}] {}",
+                chrono::Utc::now().to_rfc3339(),
+                record.level(),
+                record.target(),
+                message
+            ))
+        })
+        .level(level)
+        .chain(std::io::stderr());
+
+    if let Some(path) = log_file {
+        dispatch = dispatch.chain(fern::log_file(path)?);
+    }
+
+    #[cfg(feature = "enable_syslog")]
+    {
+        dispatch = dispatch.chain(syslog_sink()?);
+    }
+
+    dispatch.apply()?;
+
+    Ok(())
+}
+
+#[cfg(feature = "enable_syslog")]
+fn syslog_sink() -> Result<Box<dyn log::Log>> {
+    let formatter = syslog::Formatter3164 {
+        facility: syslog::Facility::LOG_USER,
+        hostname: None,
+        process: "pfm".into(),
+        pid: std::process::id(),
+    };
+
+    let logger = syslog::unix(formatter)?;
+    Ok(Box::new(syslog::BasicLogger::new(logger)))
+}
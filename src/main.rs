@@ -1,10 +1,19 @@
 use anyhow::Result;
 use clap::{App, Arg, SubCommand};
 use log::debug;
-use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
 use serde::{Deserialize, Serialize};
 use std::{collections::HashMap, env, fs::File, io::Write, path::Path};
 
+mod config;
+mod logging;
+mod portfolio;
+mod rates;
+mod stream;
+mod totp;
+mod vault;
+
+use config::Config;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct State {
     user: Option<User>,
@@ -18,11 +27,17 @@ pub struct PostUserResponse {
     auth_token: AuthToken,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PostAuthTokenResponse {
+    user: User,
+    auth_token: AuthToken,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GetExchangeRateResponse {
-    quote: String,
+    pub(crate) quote: String,
     base: String,
-    rate: f64,
+    pub(crate) rate: f64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -36,9 +51,9 @@ pub struct AuthToken {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-struct ApiError {
-    code: u16,
-    message: String,
+pub(crate) struct ApiError {
+    pub(crate) code: u16,
+    pub(crate) message: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -65,6 +80,17 @@ async fn main() {
                 .multiple(true)
                 .help("Sets the level of verbosity"),
         )
+        .arg(
+            Arg::with_name("plaintext")
+                .long("plaintext")
+                .help("Store state.json unencrypted (legacy, pre-vault behavior)"),
+        )
+        .arg(
+            Arg::with_name("log-file")
+                .long("log-file")
+                .takes_value(true)
+                .help("Also write timestamped logs to this file"),
+        )
         .subcommand(
             SubCommand::with_name("signup")
                 .about("Creates a new user")
@@ -79,9 +105,27 @@ async fn main() {
                         .required(true),
                 ),
         )
+        .subcommand(
+            SubCommand::with_name("login")
+                .about("Authenticates as an existing user")
+                .arg(Arg::with_name("username").required(true))
+                .arg(Arg::with_name("password").required(true))
+                .arg(
+                    Arg::with_name("code")
+                        .long("code")
+                        .takes_value(true)
+                        .help("6-digit two-factor code, if your account has 2FA enabled"),
+                )
+                .arg(
+                    Arg::with_name("totp-secret")
+                        .long("totp-secret")
+                        .takes_value(true)
+                        .help("Base32 TOTP secret, used to compute --code locally"),
+                ),
+        )
         .subcommand(
             SubCommand::with_name("set")
-                .about("Sets asset")
+                .about("Sets asset (creates it, or updates the amount if it already exists)")
                 .arg(
                     Arg::with_name("symbol")
                         .help("Ticker symbol or currency code")
@@ -93,45 +137,129 @@ async fn main() {
                         .required(true),
                 ),
         )
+        .subcommand(
+            SubCommand::with_name("rm")
+                .about("Removes asset")
+                .arg(
+                    Arg::with_name("symbol")
+                        .help("Ticker symbol or currency code")
+                        .required(true),
+                ),
+        )
+        .subcommand(SubCommand::with_name("list").about("Lists held assets"))
+        .subcommand(
+            SubCommand::with_name("watch")
+                .about("Streams live rates over a WebSocket and keeps reprinting the total")
+                .arg(
+                    Arg::with_name("throttle-secs")
+                        .long("throttle-secs")
+                        .takes_value(true)
+                        .default_value("1")
+                        .help("Minimum seconds between reprints"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("export")
+                .about("Exports the portfolio valuation history to a statement file")
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .takes_value(true)
+                        .possible_values(&["csv", "json"])
+                        .default_value("csv"),
+                )
+                .arg(
+                    Arg::with_name("from")
+                        .long("from")
+                        .takes_value(true)
+                        .help("RFC 3339 start date, inclusive"),
+                )
+                .arg(
+                    Arg::with_name("to")
+                        .long("to")
+                        .takes_value(true)
+                        .help("RFC 3339 end date, inclusive"),
+                )
+                .arg(
+                    Arg::with_name("out")
+                        .long("out")
+                        .takes_value(true)
+                        .default_value("statement"),
+                ),
+        )
         .get_matches();
 
-    match matches.occurrences_of("verbose") {
-        0 => {}
-        1 => env::set_var("RUST_LOG", "info"),
-        2 => env::set_var("RUST_LOG", "debug"),
-        3 | _ => env::set_var("RUST_LOG", "trace"),
-    }
+    let level = match matches.occurrences_of("verbose") {
+        0 => log::LevelFilter::Warn,
+        1 => log::LevelFilter::Info,
+        2 => log::LevelFilter::Debug,
+        _ => log::LevelFilter::Trace,
+    };
 
-    env_logger::init();
+    logging::init(level, matches.value_of("log-file")).unwrap();
 
     if env::var("RUST_BACKTRACE").is_err() {
         debug!("RUST_BACKTRACE isn't set, defaulting to \"1\"");
         env::set_var("RUST_BACKTRACE", "1");
     }
 
+    let plaintext = matches.is_present("plaintext");
+    let config = config::load().unwrap();
+
     match matches.subcommand() {
         ("signup", Some(matches)) => {
             let username = matches.value_of("username").unwrap();
             let password = matches.value_of("password").unwrap();
-            signup(username, password).await.unwrap();
+            signup(&config, username, password, plaintext).await.unwrap();
+        }
+        ("login", Some(matches)) => {
+            let username = matches.value_of("username").unwrap();
+            let password = matches.value_of("password").unwrap();
+            let code = matches.value_of("code");
+            let totp_secret = matches.value_of("totp-secret");
+            login(&config, username, password, code, totp_secret, plaintext)
+                .await
+                .unwrap();
         }
         ("set", Some(matches)) => {
             let symbol = matches.value_of("symbol").unwrap();
             let amount = matches.value_of("amount").unwrap();
-            set_currency(symbol, amount).unwrap();
+            set_currency(&config, symbol, amount, plaintext).unwrap();
+        }
+        ("rm", Some(matches)) => {
+            let symbol = matches.value_of("symbol").unwrap();
+            remove_currency(&config, symbol, plaintext).unwrap();
+        }
+        ("list", Some(_)) => {
+            list_currencies(&config, plaintext).unwrap();
+        }
+        ("watch", Some(matches)) => {
+            let throttle_secs: u64 = matches.value_of("throttle-secs").unwrap().parse().unwrap();
+            watch(&config, plaintext, std::time::Duration::from_secs(throttle_secs))
+                .await
+                .unwrap();
         }
-        _ => show_total().await.unwrap(),
+        ("export", Some(matches)) => {
+            let format = matches.value_of("format").unwrap();
+            let from = matches.value_of("from");
+            let to = matches.value_of("to");
+            let out = matches.value_of("out").unwrap();
+            let path = format!("{}.{}", out, format);
+            portfolio::export(format, from, to, Path::new(&path)).unwrap();
+            println!("Exported to {}", path);
+        }
+        _ => show_total(&config, plaintext).await.unwrap(),
     }
 }
 
-async fn signup(username: &str, password: &str) -> Result<()> {
+async fn signup(config: &Config, username: &str, password: &str, plaintext: bool) -> Result<()> {
     let mut args = HashMap::new();
     args.insert("username", username);
     args.insert("password", password);
 
     let client = reqwest::Client::new();
     let res = client
-        .post("https://api.easyportfol.io/users/")
+        .post(format!("{}/users/", config.api_url))
         .json(&args)
         .send()
         .await?;
@@ -139,10 +267,82 @@ async fn signup(username: &str, password: &str) -> Result<()> {
     if res.status().is_success() {
         let res: PostUserResponse = res.json().await?;
         println!("Signed up as {}", res.user.username);
-        let mut state = load_state()?;
+        let (mut state, passphrase) = load_state(config, plaintext)?;
+        state.user = Some(res.user.clone());
+        state.auth_token = Some(res.auth_token.clone());
+        save_state(config, &state, plaintext, passphrase.as_deref())?;
+    } else {
+        let error: ApiError = res.json().await?;
+        println!("{}", error.message);
+    }
+
+    Ok(())
+}
+
+async fn login(
+    config: &Config,
+    username: &str,
+    password: &str,
+    code: Option<&str>,
+    totp_secret: Option<&str>,
+    plaintext: bool,
+) -> Result<()> {
+    let mut code = code.map(|c| c.to_string());
+
+    if code.is_none() {
+        if let Some(secret) = totp_secret {
+            let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?;
+            code = Some(totp::generate(secret, now.as_secs())?);
+        }
+    }
+
+    let res = post_auth_token(config, username, password, code.as_deref()).await?;
+
+    if res.status().as_u16() == 428 {
+        debug!("Server requires a two-factor code, prompting");
+        println!("Enter your 6-digit two-factor code:");
+        let mut entered = String::new();
+        std::io::stdin().read_line(&mut entered)?;
+        let res = post_auth_token(config, username, password, Some(entered.trim())).await?;
+        return handle_auth_token_response(config, res, plaintext).await;
+    }
+
+    handle_auth_token_response(config, res, plaintext).await
+}
+
+async fn post_auth_token(
+    config: &Config,
+    username: &str,
+    password: &str,
+    code: Option<&str>,
+) -> Result<reqwest::Response> {
+    let mut args = HashMap::new();
+    args.insert("username", username);
+    args.insert("password", password);
+    if let Some(code) = code {
+        args.insert("totp_code", code);
+    }
+
+    let client = reqwest::Client::new();
+    Ok(client
+        .post(format!("{}/auth_tokens/", config.api_url))
+        .json(&args)
+        .send()
+        .await?)
+}
+
+async fn handle_auth_token_response(
+    config: &Config,
+    res: reqwest::Response,
+    plaintext: bool,
+) -> Result<()> {
+    if res.status().is_success() {
+        let res: PostAuthTokenResponse = res.json().await?;
+        println!("Logged in as {}", res.user.username);
+        let (mut state, passphrase) = load_state(config, plaintext)?;
         state.user = Some(res.user.clone());
         state.auth_token = Some(res.auth_token.clone());
-        save_state(&state)?;
+        save_state(config, &state, plaintext, passphrase.as_deref())?;
     } else {
         let error: ApiError = res.json().await?;
         println!("{}", error.message);
@@ -151,82 +351,184 @@ async fn signup(username: &str, password: &str) -> Result<()> {
     Ok(())
 }
 
-fn set_currency(code: &str, amount: &str) -> Result<()> {
+fn set_currency(config: &Config, code: &str, amount: &str, plaintext: bool) -> Result<()> {
     debug!("Setting {} to {}", code, amount);
     let amount = amount.parse::<f64>()?;
 
-    let mut state = load_state()?;
-    let currency = Currency {
-        code: code.to_string(),
-        amount: amount,
-    };
-    state.portfolio.currencies.push(currency);
-    save_state(&state)?;
+    let (mut state, passphrase) = load_state(config, plaintext)?;
+
+    match state
+        .portfolio
+        .currencies
+        .iter_mut()
+        .find(|c| c.code == code)
+    {
+        Some(currency) => currency.amount = amount,
+        None => state.portfolio.currencies.push(Currency {
+            code: code.to_string(),
+            amount,
+        }),
+    }
+
+    save_state(config, &state, plaintext, passphrase.as_deref())?;
 
     Ok(())
 }
 
-async fn show_total() -> Result<()> {
-    let client = reqwest::Client::new();
-    let state = load_state()?;
-    let mut total = 0.0;
+fn remove_currency(config: &Config, code: &str, plaintext: bool) -> Result<()> {
+    debug!("Removing {}", code);
+
+    let (mut state, passphrase) = load_state(config, plaintext)?;
+    state.portfolio.currencies.retain(|c| c.code != code);
+    save_state(config, &state, plaintext, passphrase.as_deref())?;
+
+    Ok(())
+}
+
+fn list_currencies(config: &Config, plaintext: bool) -> Result<()> {
+    let (state, _) = load_state(config, plaintext)?;
+
+    for currency in &state.portfolio.currencies {
+        if currency.code.to_lowercase() == "btc" {
+            println!("{}: {:.8}", currency.code, currency.amount);
+        } else {
+            println!("{}: {:.2}", currency.code, currency.amount);
+        }
+    }
+
+    Ok(())
+}
+
+async fn watch(config: &Config, plaintext: bool, throttle: std::time::Duration) -> Result<()> {
+    let (state, _) = load_state(config, plaintext)?;
+    let amounts: HashMap<String, f64> = state
+        .portfolio
+        .currencies
+        .iter()
+        .map(|c| (c.code.clone(), c.amount))
+        .collect();
+
+    let supported = stream::watch(config, &amounts, throttle).await?;
+
+    if !supported {
+        println!("Server doesn't support live streaming, falling back to a single REST snapshot");
+        show_total(config, plaintext).await?;
+    }
+
+    Ok(())
+}
+
+async fn show_total(config: &Config, plaintext: bool) -> Result<()> {
+    let (state, _) = load_state(config, plaintext)?;
+
+    let auth_token = state
+        .auth_token
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("Not logged in, please run `pfm login`"))?;
+
+    let symbols: Vec<String> = state
+        .portfolio
+        .currencies
+        .iter()
+        .map(|c| c.code.clone())
+        .collect();
+
+    let (rate_by_symbol, errors) = rates::fetch_rates(config, &auth_token.id, &symbols).await?;
 
     println!("Currencies");
     println!("---");
 
-    for currency in state.portfolio.currencies {
-        if currency.code.to_lowercase() == "btc".to_string() {
+    let mut total = 0.0;
+    let mut asset_values = HashMap::new();
+
+    for currency in &state.portfolio.currencies {
+        if currency.code.to_lowercase() == "btc" {
             println!("{}: {:.8}", currency.code, currency.amount);
         } else {
             println!("{}: {:.2}", currency.code, currency.amount);
         }
 
-        let mut headers = HeaderMap::new();
-        let header_value =
-            HeaderValue::from_str(&format!("Bearer {}", &state.auth_token.clone().unwrap().id))?;
-        headers.insert(AUTHORIZATION, header_value);
-        let builder = client
-            .get(format!(
-                "https://api.easyportfol.io/exchange_rates?quote={}&base=USD",
-                &currency.code
-            ))
-            .headers(headers);
-
-        let res = client.execute(builder.build()?).await?;
-
-        if res.status().is_success() {
-            let res: GetExchangeRateResponse = res.json().await?;
-            total += res.rate * currency.amount;
-        } else {
-            let error: ApiError = res.json().await?;
-            println!("{}", error.message);
+        if let Some(rate) = rate_by_symbol.get(&currency.code) {
+            let value = rate * currency.amount;
+            total += value;
+            asset_values.insert(currency.code.clone(), value);
         }
     }
 
     println!("---");
-    println!("Total: ${:.2}", total);
+    println!("Total: {:.2} {}", total, config.quote_currency);
+
+    if !errors.is_empty() {
+        println!("---");
+        println!("Errors (total may be incomplete):");
+        for error in &errors {
+            println!("{}: {}", error.symbol, error.message);
+        }
+    }
+
+    portfolio::append_snapshot(asset_values, total, !errors.is_empty())?;
 
     Ok(())
 }
 
-fn save_state(state: &State) -> Result<()> {
-    let mut file = File::create("state.json")?;
+/// Writes `state` to disk. `passphrase` should be the one returned by the
+/// preceding `load_state` call, so a vault is never re-encrypted under a
+/// different (possibly mistyped) passphrase than the one that opened it.
+/// When `None` (no vault existed yet), a fresh passphrase is prompted for
+/// and confirmed before use.
+fn save_state(
+    config: &Config,
+    state: &State,
+    plaintext: bool,
+    passphrase: Option<&str>,
+) -> Result<()> {
+    let mut file = File::create(&config.state_path)?;
     let json = serde_json::to_string_pretty(state)?;
-    write!(file, "{}", json)?;
+
+    if plaintext {
+        write!(file, "{}", json)?;
+    } else {
+        let owned_passphrase;
+        let passphrase = match passphrase {
+            Some(passphrase) => passphrase,
+            None => {
+                owned_passphrase = vault::read_passphrase_confirmed(
+                    "Master passphrase: ",
+                    "Confirm master passphrase: ",
+                )?;
+                &owned_passphrase
+            }
+        };
+        let envelope = vault::encrypt(json.as_bytes(), passphrase)?;
+        write!(file, "{}", serde_json::to_string_pretty(&envelope)?)?;
+    }
+
     Ok(())
 }
 
-fn load_state() -> Result<State> {
-    let file_path = Path::new("state.json");
+/// Loads `State` from disk, along with the passphrase that decrypted it
+/// (`None` if the vault didn't exist yet, or `--plaintext` is in effect),
+/// so callers can reuse it for a subsequent `save_state` without re-prompting.
+fn load_state(config: &Config, plaintext: bool) -> Result<(State, Option<String>)> {
+    let file_path = Path::new(&config.state_path);
 
-    return if file_path.exists() {
-        let file = File::open(file_path)?;
-        Ok(serde_json::from_reader(file)?)
-    } else {
-        Ok(State {
+    if !file_path.exists() {
+        let state = State {
             user: None,
             auth_token: None,
             portfolio: Portfolio { currencies: vec![] },
-        })
-    };
+        };
+        return Ok((state, None));
+    }
+
+    let file = File::open(file_path)?;
+
+    if plaintext {
+        return Ok((serde_json::from_reader(file)?, None));
+    }
+
+    let envelope: vault::Envelope = serde_json::from_reader(file)?;
+    let passphrase = vault::read_passphrase("Master passphrase: ")?;
+    let json = vault::decrypt(&envelope, &passphrase)?;
+    Ok((serde_json::from_slice(&json)?, Some(passphrase)))
 }
@@ -0,0 +1,121 @@
+//! Portfolio valuation history and statement export.
+//!
+//! Every `show_total` appends a timestamped snapshot of the portfolio to an
+//! append-only JSON-lines log, so users can later export their valuation
+//! history into a spreadsheet or tax tool.
+
+use anyhow::Result;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::Path,
+};
+
+const HISTORY_PATH: &str = "history.jsonl";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub date: String,
+    pub assets: HashMap<String, f64>,
+    pub total: f64,
+    /// Set when one or more rates failed to fetch, so `total` omits those
+    /// assets and understates the real valuation. Defaults to `false` when
+    /// reading snapshots written before this field existed.
+    #[serde(default)]
+    pub incomplete: bool,
+}
+
+/// Appends a snapshot to the history log. Called once per `show_total`.
+/// `incomplete` should be set whenever a rate fetch failed for one or more
+/// held assets, so exported statements don't silently understate the total.
+pub fn append_snapshot(assets: HashMap<String, f64>, total: f64, incomplete: bool) -> Result<()> {
+    let snapshot = Snapshot {
+        date: Utc::now().to_rfc3339(),
+        assets,
+        total,
+        incomplete,
+    };
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(HISTORY_PATH)?;
+
+    writeln!(file, "{}", serde_json::to_string(&snapshot)?)?;
+
+    Ok(())
+}
+
+fn load_history() -> Result<Vec<Snapshot>> {
+    let path = Path::new(HISTORY_PATH);
+
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+
+    let reader = BufReader::new(File::open(path)?);
+    let mut snapshots = vec![];
+
+    for line in reader.lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            snapshots.push(serde_json::from_str(&line)?);
+        }
+    }
+
+    Ok(snapshots)
+}
+
+/// Writes the history log (optionally filtered by an inclusive `[from, to]`
+/// date range, compared lexicographically against the RFC 3339 timestamps)
+/// to `out_path` in `format` ("csv" or "json").
+pub fn export(format: &str, from: Option<&str>, to: Option<&str>, out_path: &Path) -> Result<()> {
+    let snapshots: Vec<Snapshot> = load_history()?
+        .into_iter()
+        .filter(|s| from.map_or(true, |from| s.date.as_str() >= from))
+        .filter(|s| to.map_or(true, |to| s.date.as_str() <= to))
+        .collect();
+
+    let mut file = File::create(out_path)?;
+
+    match format {
+        "json" => write!(file, "{}", serde_json::to_string_pretty(&snapshots)?)?,
+        "csv" => {
+            let mut symbols: Vec<&String> = snapshots
+                .iter()
+                .flat_map(|s| s.assets.keys())
+                .collect::<std::collections::BTreeSet<_>>()
+                .into_iter()
+                .collect();
+            symbols.sort();
+
+            let mut header = vec!["date".to_string()];
+            header.extend(symbols.iter().map(|s| s.to_string()));
+            header.push("total".to_string());
+            header.push("incomplete".to_string());
+            writeln!(file, "{}", header.join(","))?;
+
+            for snapshot in &snapshots {
+                let mut row = vec![snapshot.date.clone()];
+                for symbol in &symbols {
+                    row.push(
+                        snapshot
+                            .assets
+                            .get(*symbol)
+                            .map(|v| format!("{:.8}", v))
+                            .unwrap_or_default(),
+                    );
+                }
+                row.push(format!("{:.2}", snapshot.total));
+                row.push(snapshot.incomplete.to_string());
+                writeln!(file, "{}", row.join(","))?;
+            }
+        }
+        other => anyhow::bail!("unsupported export format: {}", other),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,171 @@
+//! Exchange-rate fetching for `show_total`.
+//!
+//! Tries a single batched request for all held symbols first, and falls
+//! back to one bounded-concurrency request per symbol when the server
+//! doesn't support batching. Per-symbol failures are collected rather than
+//! aborting the whole total.
+
+use crate::config::Config;
+use crate::{ApiError, GetExchangeRateResponse};
+use anyhow::Result;
+use futures::stream::{self, StreamExt};
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+const MAX_CONCURRENT_REQUESTS: usize = 8;
+
+#[derive(Debug, Deserialize)]
+struct GetExchangeRatesResponse {
+    rates: Vec<GetExchangeRateResponse>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RateError {
+    pub symbol: String,
+    pub message: String,
+}
+
+fn auth_headers(auth_token_id: &str) -> Result<HeaderMap> {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        AUTHORIZATION,
+        HeaderValue::from_str(&format!("Bearer {}", auth_token_id))?,
+    );
+    Ok(headers)
+}
+
+/// Outcome of trying the batched `quote=BTC,ETH,USD` endpoint.
+enum BatchOutcome {
+    /// The server doesn't support batching (404/501) — fall back silently.
+    Unsupported,
+    Rates(HashMap<String, f64>),
+    /// The server supports batching but the request itself failed (e.g. a
+    /// bad auth token) — this is a real error, not a reason to fall back.
+    Error(String),
+}
+
+/// Fetches rates for `symbols` against `config.quote_currency`, returning
+/// the rates it could get and a report of per-symbol errors.
+pub async fn fetch_rates(
+    config: &Config,
+    auth_token_id: &str,
+    symbols: &[String],
+) -> Result<(HashMap<String, f64>, Vec<RateError>)> {
+    match fetch_batched(config, auth_token_id, symbols).await? {
+        BatchOutcome::Rates(rates) => {
+            let errors = symbols
+                .iter()
+                .filter(|symbol| !rates.contains_key(symbol.as_str()))
+                .map(|symbol| RateError {
+                    symbol: symbol.clone(),
+                    message: "missing from batched exchange_rates response".to_string(),
+                })
+                .collect();
+
+            Ok((rates, errors))
+        }
+        BatchOutcome::Error(message) => {
+            // One failed request, reported once per held symbol rather than
+            // retried per symbol — a bad token shouldn't turn into N failed
+            // concurrent requests.
+            let errors = symbols
+                .iter()
+                .map(|symbol| RateError {
+                    symbol: symbol.clone(),
+                    message: message.clone(),
+                })
+                .collect();
+
+            Ok((HashMap::new(), errors))
+        }
+        BatchOutcome::Unsupported => Ok(fetch_concurrently(config, auth_token_id, symbols).await),
+    }
+}
+
+/// A single `quote=BTC,ETH,USD` request.
+async fn fetch_batched(
+    config: &Config,
+    auth_token_id: &str,
+    symbols: &[String],
+) -> Result<BatchOutcome> {
+    let client = reqwest::Client::new();
+    let res = client
+        .get(format!(
+            "{}/exchange_rates?quote={}&base={}",
+            config.api_url,
+            symbols.join(","),
+            config.quote_currency
+        ))
+        .headers(auth_headers(auth_token_id)?)
+        .send()
+        .await?;
+
+    if res.status() == reqwest::StatusCode::NOT_FOUND
+        || res.status() == reqwest::StatusCode::NOT_IMPLEMENTED
+    {
+        return Ok(BatchOutcome::Unsupported);
+    }
+
+    if !res.status().is_success() {
+        let error: ApiError = res.json().await?;
+        return Ok(BatchOutcome::Error(error.message));
+    }
+
+    let res: GetExchangeRatesResponse = res.json().await?;
+    Ok(BatchOutcome::Rates(
+        res.rates.into_iter().map(|r| (r.quote.clone(), r.rate)).collect(),
+    ))
+}
+
+async fn fetch_concurrently(
+    config: &Config,
+    auth_token_id: &str,
+    symbols: &[String],
+) -> (HashMap<String, f64>, Vec<RateError>) {
+    let results = stream::iter(symbols.iter().cloned())
+        .map(|symbol| async move {
+            let result = fetch_one(config, auth_token_id, &symbol).await;
+            (symbol, result)
+        })
+        .buffer_unordered(MAX_CONCURRENT_REQUESTS)
+        .collect::<Vec<_>>()
+        .await;
+
+    let mut rates = HashMap::new();
+    let mut errors = vec![];
+
+    for (symbol, result) in results {
+        match result {
+            Ok(rate) => {
+                rates.insert(symbol, rate);
+            }
+            Err(e) => errors.push(RateError {
+                symbol,
+                message: e.to_string(),
+            }),
+        }
+    }
+
+    (rates, errors)
+}
+
+async fn fetch_one(config: &Config, auth_token_id: &str, symbol: &str) -> Result<f64> {
+    let client = reqwest::Client::new();
+    let res = client
+        .get(format!(
+            "{}/exchange_rates?quote={}&base={}",
+            config.api_url, symbol, config.quote_currency
+        ))
+        .headers(auth_headers(auth_token_id)?)
+        .send()
+        .await?;
+
+    if res.status().is_success() {
+        let res: GetExchangeRateResponse = res.json().await?;
+        Ok(res.rate)
+    } else {
+        let error: ApiError = res.json().await?;
+        Err(anyhow::anyhow!(error.message))
+    }
+}
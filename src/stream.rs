@@ -0,0 +1,155 @@
+//! Live price streaming for the `watch` subcommand.
+//!
+//! Speaks a small JSON-RPC 2.0 pub-sub dialect over a WebSocket: we send a
+//! `subscribe` request listing the symbols we hold, the server acknowledges
+//! with a subscription id, then pushes `rate_update` notifications tagged
+//! with that id as quotes change.
+
+use crate::config::Config;
+use anyhow::Result;
+use futures::{SinkExt, StreamExt};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, time::Duration};
+use tokio_tungstenite::tungstenite::Message;
+
+#[derive(Debug, Serialize)]
+struct SubscribeRequest<'a> {
+    jsonrpc: &'static str,
+    id: u64,
+    method: &'static str,
+    params: SubscribeParams<'a>,
+}
+
+#[derive(Debug, Serialize)]
+struct SubscribeParams<'a> {
+    symbols: &'a [String],
+}
+
+#[derive(Debug, Deserialize)]
+struct SubscribeResponse {
+    id: u64,
+    result: Option<SubscribeResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SubscribeResult {
+    subscription: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct RateNotification {
+    method: String,
+    params: RateUpdate,
+}
+
+#[derive(Debug, Deserialize)]
+struct RateUpdate {
+    subscription: String,
+    symbol: String,
+    rate: f64,
+}
+
+/// Opens a WebSocket connection, subscribes to `symbols`, and reprints the
+/// portfolio total on every pushed rate update, at most once per `throttle`.
+///
+/// Returns `Ok(false)` if the server doesn't speak the subscribe protocol,
+/// so the caller can fall back to the REST path.
+pub async fn watch(
+    config: &Config,
+    amounts: &HashMap<String, f64>,
+    throttle: Duration,
+) -> Result<bool> {
+    let ws_url = config.api_url.replacen("http", "ws", 1) + "/ws";
+    debug!("Connecting to {}", ws_url);
+
+    let (ws_stream, _) = match tokio_tungstenite::connect_async(&ws_url).await {
+        Ok(conn) => conn,
+        Err(e) => {
+            debug!("WebSocket connect failed, falling back to REST: {}", e);
+            return Ok(false);
+        }
+    };
+
+    let (mut write, mut read) = ws_stream.split();
+
+    let symbols: Vec<String> = amounts.keys().cloned().collect();
+    let subscribe = SubscribeRequest {
+        jsonrpc: "2.0",
+        id: 1,
+        method: "subscribe",
+        params: SubscribeParams { symbols: &symbols },
+    };
+    write
+        .send(Message::Text(serde_json::to_string(&subscribe)?))
+        .await?;
+
+    let mut subscription_id = None;
+    let mut rates: HashMap<String, f64> = HashMap::new();
+    let mut last_print = std::time::Instant::now() - throttle;
+
+    while let Some(message) = read.next().await {
+        let text = match message? {
+            Message::Text(text) => text,
+            Message::Close(_) => break,
+            _ => continue,
+        };
+
+        if subscription_id.is_none() {
+            if let Ok(response) = serde_json::from_str::<SubscribeResponse>(&text) {
+                if response.id == 1 {
+                    match response.result {
+                        Some(result) => subscription_id = Some(result.subscription),
+                        None => return Ok(false),
+                    }
+                    continue;
+                }
+            }
+        }
+
+        let notification: RateNotification = match serde_json::from_str(&text) {
+            Ok(n) => n,
+            Err(_) => continue,
+        };
+
+        if notification.method != "rate_update" {
+            continue;
+        }
+        if Some(&notification.params.subscription) != subscription_id.as_ref() {
+            continue;
+        }
+
+        rates.insert(notification.params.symbol, notification.params.rate);
+
+        if last_print.elapsed() < throttle {
+            continue;
+        }
+        last_print = std::time::Instant::now();
+
+        print_total(config, amounts, &rates);
+    }
+
+    Ok(true)
+}
+
+fn print_total(config: &Config, amounts: &HashMap<String, f64>, rates: &HashMap<String, f64>) {
+    println!("Currencies");
+    println!("---");
+
+    let mut total = 0.0;
+
+    for (symbol, amount) in amounts {
+        let rate = rates.get(symbol).copied().unwrap_or(0.0);
+        let value = rate * amount;
+        total += value;
+
+        if symbol.to_lowercase() == "btc" {
+            println!("{}: {:.8}", symbol, amount);
+        } else {
+            println!("{}: {:.2}", symbol, amount);
+        }
+    }
+
+    println!("---");
+    println!("Total: {:.2} {}", total, config.quote_currency);
+}
@@ -0,0 +1,64 @@
+//! RFC 6238 TOTP (and the underlying RFC 4226 HOTP) for local two-factor
+//! code generation, so `--totp-secret` can be used non-interactively.
+
+use anyhow::{anyhow, Result};
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+
+const TIME_STEP_SECS: u64 = 30;
+const DIGITS: u32 = 6;
+
+type HmacSha1 = Hmac<Sha1>;
+
+/// HOTP, per RFC 4226: HMAC-SHA1 over the counter, then dynamic truncation.
+fn hotp(secret: &[u8], counter: u64) -> Result<u32> {
+    let mut mac = HmacSha1::new_from_slice(secret).map_err(|e| anyhow!("invalid secret: {}", e))?;
+    mac.update(&counter.to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let truncated = ((digest[offset] as u32 & 0x7f) << 24)
+        | ((digest[offset + 1] as u32) << 16)
+        | ((digest[offset + 2] as u32) << 8)
+        | (digest[offset + 3] as u32);
+
+    Ok(truncated % 10u32.pow(DIGITS))
+}
+
+/// TOTP, per RFC 6238: HOTP with the counter derived from the current Unix
+/// time divided into 30-second steps.
+pub fn generate(secret_base32: &str, unix_time_secs: u64) -> Result<String> {
+    let secret = data_encoding::BASE32_NOPAD
+        .decode(secret_base32.to_uppercase().as_bytes())
+        .map_err(|e| anyhow!("TOTP secret is not valid base32: {}", e))?;
+
+    let counter = unix_time_secs / TIME_STEP_SECS;
+    let code = hotp(&secret, counter)?;
+
+    Ok(format!("{:0width$}", code, width = DIGITS as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RFC 6238 Appendix B test vectors (SHA1, 8-digit truncation); the
+    // expected values here are those 8-digit codes' last 6 digits, since
+    // mod 10^6 and mod 10^8 agree on the low 6 digits.
+    const SECRET_BASE32: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    #[test]
+    fn matches_rfc_6238_test_vectors() {
+        let vectors = [
+            (59, "287082"),
+            (1111111109, "081804"),
+            (1111111111, "050471"),
+            (1234567890, "005924"),
+            (2000000000, "279037"),
+        ];
+
+        for (unix_time_secs, expected) in vectors {
+            assert_eq!(generate(SECRET_BASE32, unix_time_secs).unwrap(), expected);
+        }
+    }
+}
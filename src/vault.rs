@@ -0,0 +1,196 @@
+//! Encrypted-at-rest storage for `State`.
+//!
+//! `state.json` used to be plain JSON, which meant the bearer token and the
+//! whole portfolio were readable by anyone with filesystem access. This
+//! module wraps the serialized state in an envelope encrypted with an AEAD
+//! cipher, keyed by a passphrase-derived key.
+
+use anyhow::{anyhow, Result};
+use argon2::Argon2;
+use chacha20poly1305::{
+    aead::{Aead, KeyInit},
+    XChaCha20Poly1305, XNonce,
+};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+const ENVELOPE_VERSION: u8 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+const KEY_LEN: usize = 32;
+
+/// Argon2id parameters used to derive the encryption key from a passphrase.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KdfParams {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        KdfParams {
+            memory_kib: 19 * 1024,
+            iterations: 2,
+            parallelism: 1,
+        }
+    }
+}
+
+/// On-disk representation of an encrypted `State`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Envelope {
+    pub version: u8,
+    pub kdf_params: KdfParams,
+    #[serde(with = "base64_bytes")]
+    pub salt: Vec<u8>,
+    #[serde(with = "base64_bytes")]
+    pub nonce: Vec<u8>,
+    #[serde(with = "base64_bytes")]
+    pub ciphertext: Vec<u8>,
+}
+
+mod base64_bytes {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&base64::encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(d)?;
+        base64::decode(&encoded).map_err(serde::de::Error::custom)
+    }
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], params: &KdfParams) -> Result<[u8; KEY_LEN]> {
+    let argon2_params = argon2::Params::new(
+        params.memory_kib,
+        params.iterations,
+        params.parallelism,
+        Some(KEY_LEN),
+    )
+    .map_err(|e| anyhow!("invalid KDF params: {}", e))?;
+
+    let argon2 = Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, argon2_params);
+
+    let mut key = [0u8; KEY_LEN];
+    argon2
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| anyhow!("key derivation failed: {}", e))?;
+
+    Ok(key)
+}
+
+/// Encrypts `plaintext` (typically a serialized `State`) under `passphrase`.
+pub fn encrypt(plaintext: &[u8], passphrase: &str) -> Result<Envelope> {
+    let mut rng = rand::thread_rng();
+
+    let mut salt = vec![0u8; SALT_LEN];
+    rng.fill_bytes(&mut salt);
+
+    let mut nonce_bytes = vec![0u8; NONCE_LEN];
+    rng.fill_bytes(&mut nonce_bytes);
+
+    let kdf_params = KdfParams::default();
+    let key = derive_key(passphrase, &salt, &kdf_params)?;
+
+    let cipher = XChaCha20Poly1305::new((&key).into());
+    let nonce = XNonce::from_slice(&nonce_bytes);
+    let ciphertext = cipher
+        .encrypt(nonce, plaintext)
+        .map_err(|_| anyhow!("encryption failed"))?;
+
+    Ok(Envelope {
+        version: ENVELOPE_VERSION,
+        kdf_params,
+        salt,
+        nonce: nonce_bytes,
+        ciphertext,
+    })
+}
+
+/// Decrypts an `Envelope`, failing loudly on a MAC mismatch (wrong
+/// passphrase or tampered file).
+pub fn decrypt(envelope: &Envelope, passphrase: &str) -> Result<Vec<u8>> {
+    if envelope.version != ENVELOPE_VERSION {
+        return Err(anyhow!("unsupported vault version: {}", envelope.version));
+    }
+
+    if envelope.salt.len() != SALT_LEN {
+        return Err(anyhow!("corrupted vault: salt has the wrong length"));
+    }
+    if envelope.nonce.len() != NONCE_LEN {
+        return Err(anyhow!("corrupted vault: nonce has the wrong length"));
+    }
+
+    let key = derive_key(passphrase, &envelope.salt, &envelope.kdf_params)?;
+    let cipher = XChaCha20Poly1305::new((&key).into());
+    let nonce = XNonce::from_slice(&envelope.nonce);
+
+    cipher
+        .decrypt(nonce, envelope.ciphertext.as_ref())
+        .map_err(|_| anyhow!("wrong passphrase or corrupted vault (MAC mismatch)"))
+}
+
+/// Reads the master passphrase from `PFM_PASSPHRASE` if set (for scripting),
+/// otherwise prompts interactively.
+pub fn read_passphrase(prompt: &str) -> Result<String> {
+    if let Ok(passphrase) = std::env::var("PFM_PASSPHRASE") {
+        return Ok(passphrase);
+    }
+
+    Ok(rpassword::prompt_password(prompt)?)
+}
+
+/// Like `read_passphrase`, but for creating a brand new vault: prompts
+/// twice and fails if the two entries don't match, so a typo can't silently
+/// lock the only copy of `state.json` under an unknown key.
+pub fn read_passphrase_confirmed(prompt: &str, confirm_prompt: &str) -> Result<String> {
+    if let Ok(passphrase) = std::env::var("PFM_PASSPHRASE") {
+        return Ok(passphrase);
+    }
+
+    let passphrase = rpassword::prompt_password(prompt)?;
+    let confirmation = rpassword::prompt_password(confirm_prompt)?;
+
+    if passphrase != confirmation {
+        return Err(anyhow!("passphrases didn't match"));
+    }
+
+    Ok(passphrase)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encrypt_then_decrypt_round_trips() {
+        let plaintext = b"{\"auth_token\":{\"id\":\"secret\"}}";
+        let envelope = encrypt(plaintext, "correct horse battery staple").unwrap();
+
+        let decrypted = decrypt(&envelope, "correct horse battery staple").unwrap();
+
+        assert_eq!(decrypted, plaintext);
+    }
+
+    #[test]
+    fn decrypt_fails_on_wrong_passphrase() {
+        let envelope = encrypt(b"top secret", "correct horse battery staple").unwrap();
+
+        let result = decrypt(&envelope, "wrong passphrase");
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn decrypt_returns_err_instead_of_panicking_on_truncated_nonce() {
+        let mut envelope = encrypt(b"top secret", "correct horse battery staple").unwrap();
+        envelope.nonce.truncate(4);
+
+        let result = decrypt(&envelope, "correct horse battery staple");
+
+        assert!(result.is_err());
+    }
+}